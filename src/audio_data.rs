@@ -1,15 +1,13 @@
-use crate::listenbrainz_client::ListenbrainzClient;
+use crate::cache::{CacheLookup, ResolutionCache};
+use crate::resolver::MetadataResolver;
 use anyhow::{anyhow, Result};
 use audiotags::Tag;
-use cached::proc_macro::cached;
 use lofty::{file::TaggedFileExt, tag::ItemKey};
 use log::debug;
-use musicbrainz_rs::entity::artist::{Artist, ArtistSearchQuery};
-use musicbrainz_rs::Search;
-use serde_json::Value;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::str::FromStr;
-use url::Url;
+use std::sync::Mutex;
 use uuid::Uuid;
 
 #[derive(Debug, Eq, PartialEq)]
@@ -25,99 +23,149 @@ pub enum AudioIDData {
     AudioFileData(AudioFileData),
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ArtistData {
     pub artist_tag: String,
     pub mbid: Option<Uuid>,
 }
 
 pub async fn get_musicbrainz_id_for_audio_data(
-    listenbrainz_client: &mut ListenbrainzClient,
+    resolver: &mut impl MetadataResolver,
     audio_file_data: AudioFileData,
+    non_interactive: bool,
+    cache: &Mutex<ResolutionCache>,
 ) -> Result<Uuid> {
-    let mut result = make_listenbrainz_lookup_request(
-        listenbrainz_client,
-        &audio_file_data.title,
+    match cache.lock().unwrap().lookup_recording(
         &audio_file_data.artist,
-    )
-    .await?;
-
-    if result.as_object().unwrap().is_empty() {
-        // Attempt to resolve artist and try that, it might be an alias
-        let artist = get_artist_mbid(audio_file_data.artist.clone()).await;
-        result = make_listenbrainz_lookup_request(
-            listenbrainz_client,
-            &audio_file_data.title,
-            &artist.artist_tag,
-        )
-        .await?;
+        &audio_file_data.title,
+        audio_file_data.album.as_deref(),
+    ) {
+        CacheLookup::Hit(mbid) => return Ok(mbid),
+        CacheLookup::Negative => {
+            return Err(anyhow!(
+                "{:?} is cached as unresolved, skipping",
+                audio_file_data
+            ))
+        }
+        CacheLookup::Miss => {}
     }
 
-    if result.as_object().unwrap().is_empty() {
-        return Err(anyhow::anyhow!("Could not resolve {:?}", audio_file_data));
-    }
+    let result =
+        resolve_musicbrainz_id_for_audio_data(resolver, &audio_file_data, non_interactive, cache)
+            .await;
 
-    let out = result
-        .get("recording_mbid")
-        .ok_or_else(|| anyhow::anyhow!("Could not extract recording MBID from JSON: {:?}", result))?
-        .as_str()
-        .ok_or_else(|| anyhow!("Could not convert to string"))?;
-    let mbid = Uuid::from_str(out)?;
-    Ok(mbid)
+    match result {
+        Ok(Some(mbid)) => {
+            cache.lock().unwrap().store_recording(
+                &audio_file_data.artist,
+                &audio_file_data.title,
+                audio_file_data.album.as_deref(),
+                Some(mbid),
+            );
+            Ok(mbid)
+        }
+        // All three engines were exhausted and genuinely found nothing; this, and only
+        // this, is worth remembering as a negative result.
+        Ok(None) => {
+            cache.lock().unwrap().store_recording(
+                &audio_file_data.artist,
+                &audio_file_data.title,
+                audio_file_data.album.as_deref(),
+                None,
+            );
+            Err(anyhow!("Could not resolve {:?}", audio_file_data))
+        }
+        // A transient failure (network timeout, MusicBrainz 5xx, rate limit, malformed
+        // JSON) rather than a real no-match; don't poison the cache with it, so the next
+        // run retries instead of trusting a one-off glitch for `NEGATIVE_RESULT_TTL_SECS`.
+        Err(e) => Err(e),
+    }
 }
 
-async fn make_listenbrainz_lookup_request(
-    listenbrainz_client: &mut ListenbrainzClient,
-    title: &String,
-    artist: &String,
-) -> Result<Value> {
-    let request_url: Url = Url::parse_with_params(
-        "https://api.listenbrainz.org/1/metadata/lookup/",
-        &[("artist_name", artist), ("recording_name", title)],
-    )?;
-    let result = listenbrainz_client
-        .take_request_builder(listenbrainz_client.request_client.get(request_url))
+async fn resolve_musicbrainz_id_for_audio_data(
+    resolver: &mut impl MetadataResolver,
+    audio_file_data: &AudioFileData,
+    non_interactive: bool,
+    cache: &Mutex<ResolutionCache>,
+) -> Result<Option<Uuid>> {
+    if let Some(mbid) = resolver
+        .lookup_recording(
+            &audio_file_data.title,
+            &audio_file_data.artist,
+            audio_file_data.album.as_deref(),
+            non_interactive,
+        )
         .await?
-        .error_for_status()?
-        .json::<Value>()
-        .await?;
-    Ok(result)
-}
-
-#[cached]
-async fn get_artist_mbid(artist_name: String) -> ArtistData {
-    let query = ArtistSearchQuery::query_builder()
-        .artist(artist_name.as_str())
-        .build();
-    let mut result = Artist::search(query)
-        .execute()
-        .await
-        .expect("Could not make search");
+    {
+        return Ok(Some(mbid));
+    }
 
-    // If no results found, find an alias instead
-    if result.count <= 0 {
-        let query = ArtistSearchQuery::query_builder()
-            .alias(artist_name.as_str())
-            .build();
-        result = Artist::search(query)
-            .execute()
-            .await
-            .expect("Could not make search");
+    // Attempt to resolve artist and try that, it might be an alias
+    let artist = get_artist_mbid(
+        resolver,
+        audio_file_data.artist.clone(),
+        non_interactive,
+        cache,
+    )
+    .await?;
+    if artist.artist_tag != audio_file_data.artist {
+        if let Some(mbid) = resolver
+            .lookup_recording(
+                &audio_file_data.title,
+                &artist.artist_tag,
+                audio_file_data.album.as_deref(),
+                non_interactive,
+            )
+            .await?
+        {
+            return Ok(Some(mbid));
+        }
     }
 
-    if result.count <= 0 {
-        return ArtistData {
-            artist_tag: artist_name.clone(),
-            mbid: None,
-        };
+    // Both the direct lookup and the alias retry came up empty; fall back to a
+    // MusicBrainz recording search, which can find recordings the ListenBrainz lookup
+    // endpoint's stricter matching misses.
+    if let Some(mbid) = resolver
+        .search_recording(
+            &audio_file_data.title,
+            &audio_file_data.artist,
+            audio_file_data.album.as_deref(),
+            non_interactive,
+        )
+        .await?
+    {
+        return Ok(Some(mbid));
     }
 
-    // TODO: need to do something clever here too to find the best one
-    let artist = result.entities.first().unwrap();
-    ArtistData {
-        artist_tag: artist.name.clone(),
-        mbid: Some(Uuid::from_str(artist.id.as_str()).expect("Could not convert to valid UUID")),
+    // All engines agree: genuinely not found, as opposed to one of them erroring out.
+    Ok(None)
+}
+
+async fn get_artist_mbid(
+    resolver: &impl MetadataResolver,
+    artist_name: String,
+    non_interactive: bool,
+    cache: &Mutex<ResolutionCache>,
+) -> Result<ArtistData> {
+    match cache.lock().unwrap().lookup_artist(&artist_name) {
+        CacheLookup::Hit(data) => return Ok(data),
+        CacheLookup::Negative => {
+            return Ok(ArtistData {
+                artist_tag: artist_name,
+                mbid: None,
+            })
+        }
+        CacheLookup::Miss => {}
     }
+
+    let resolved = resolver
+        .search_artist(&artist_name, non_interactive)
+        .await?;
+    cache
+        .lock()
+        .unwrap()
+        .store_artist(&artist_name, resolved.clone());
+    Ok(resolved)
 }
 
 pub fn load_tags_from_file_path(file: PathBuf) -> Result<AudioIDData> {
@@ -169,144 +217,173 @@ pub fn read_mbid_from_metadata(file: &PathBuf) -> Result<Uuid> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::resolver::MockResolver;
+
     #[test]
-    fn test_get_recording_mbid_general_1() {
+    fn test_get_recording_mbid_direct_hit() {
         let test = AudioFileData {
-            artist: "Ed Sheeran".parse().unwrap(),
-            title: "Perfect".parse().unwrap(),
+            artist: "Ed Sheeran".to_string(),
+            title: "Perfect".to_string(),
             album: Some("Divide".to_string()),
         };
-        let mut test_client = ListenbrainzClient::new("".to_string());
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        let result = rt.block_on(async {
-            get_musicbrainz_id_for_audio_data(&mut test_client, test)
-                .await
-                .unwrap()
-        });
-        assert_eq!(result.to_string(), "b84dd2d1-2bf1-4fcc-aadc-6cc39c36ba35");
-    }
+        let expected = Uuid::new_v4();
+        let mut resolver = MockResolver::default();
+        resolver
+            .recordings
+            .insert(("Ed Sheeran".to_string(), "Perfect".to_string()), expected);
+        let cache = Mutex::new(ResolutionCache::default());
 
-    #[test]
-    fn test_get_recording_mbid_artist_alias() {
-        let test = AudioFileData {
-            artist: "Akihito Okano".parse().unwrap(),
-            title: "光あれ".parse().unwrap(),
-            album: Some("光あれ".parse().unwrap()),
-        };
-        let mut test_client = ListenbrainzClient::new("".to_string());
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let result = rt.block_on(async {
-            get_musicbrainz_id_for_audio_data(&mut test_client, test)
-                .await
-                .unwrap()
-        });
-        assert_eq!(result.to_string(), "5d93f99e-6663-4e77-97f1-0835f6b96b00");
+        let result = rt
+            .block_on(get_musicbrainz_id_for_audio_data(
+                &mut resolver,
+                test,
+                true,
+                &cache,
+            ))
+            .unwrap();
+        assert_eq!(result, expected);
     }
 
     #[test]
-    fn test_get_recording_mbid_two_artists_and_join() {
+    fn test_get_recording_mbid_falls_back_to_artist_alias() {
         let test = AudioFileData {
-            artist: "Ed Sheeran & Beyonce".parse().unwrap(),
-            title: "Perfect Duet".parse().unwrap(),
+            artist: "Akihito Okano".to_string(),
+            title: "光あれ".to_string(),
             album: None,
         };
-        let mut test_client = ListenbrainzClient::new("".to_string());
+        let expected = Uuid::new_v4();
+        let mut resolver = MockResolver::default();
+        resolver.artists.insert(
+            "Akihito Okano".to_string(),
+            ArtistData {
+                artist_tag: "岡野昭仁".to_string(),
+                mbid: Some(Uuid::new_v4()),
+            },
+        );
+        resolver
+            .recordings
+            .insert(("岡野昭仁".to_string(), "光あれ".to_string()), expected);
+        let cache = Mutex::new(ResolutionCache::default());
+
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let result = rt.block_on(async {
-            get_musicbrainz_id_for_audio_data(&mut test_client, test)
-                .await
-                .unwrap()
-        });
-        assert_eq!(result.to_string(), "764f4c40-1c16-44a7-a6e6-b8c426604b57");
+        let result = rt
+            .block_on(get_musicbrainz_id_for_audio_data(
+                &mut resolver,
+                test,
+                true,
+                &cache,
+            ))
+            .unwrap();
+        assert_eq!(result, expected);
     }
 
     #[test]
-    fn test_get_recording_mbid_band_name_with_character() {
+    fn test_get_recording_mbid_falls_back_to_recording_search() {
         let test = AudioFileData {
-            artist: "Florence + the Machine".parse().unwrap(),
-            title: "Never Let Me Go".parse().unwrap(),
-            album: None,
+            artist: "Ed Sheeran".to_string(),
+            title: "Perfect".to_string(),
+            album: Some("Divide".to_string()),
         };
-        let mut test_client = ListenbrainzClient::new("".to_string());
+        let expected = Uuid::new_v4();
+        let mut resolver = MockResolver::default();
+        resolver
+            .recording_search_results
+            .insert(("Ed Sheeran".to_string(), "Perfect".to_string()), expected);
+        let cache = Mutex::new(ResolutionCache::default());
+
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let result = rt.block_on(async {
-            get_musicbrainz_id_for_audio_data(&mut test_client, test)
-                .await
-                .unwrap()
-        });
-        assert_eq!(result.to_string(), "589b2eff-e541-475b-bbe7-ca778238e711");
+        let result = rt
+            .block_on(get_musicbrainz_id_for_audio_data(
+                &mut resolver,
+                test,
+                true,
+                &cache,
+            ))
+            .unwrap();
+        assert_eq!(result, expected);
     }
 
     #[test]
-    fn test_get_recording_mbid_two_artist_feat_join() {
+    fn test_get_recording_mbid_fails_when_unresolvable() {
         let test = AudioFileData {
-            artist: "Justin Bieber feat. Khalid".parse().unwrap(),
-            title: "As I Am".parse().unwrap(),
+            artist: "Ed Sheeran".to_string(),
+            title: "Asdjkhfgds".to_string(),
             album: None,
         };
-        let mut test_client = ListenbrainzClient::new("".to_string());
+        let mut resolver = MockResolver::default();
+        let cache = Mutex::new(ResolutionCache::default());
+
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let result = rt.block_on(async {
-            get_musicbrainz_id_for_audio_data(&mut test_client, test)
-                .await
-                .unwrap()
-        });
-        assert_eq!(result.to_string(), "4f8268ae-8db1-42a7-baca-b1a0b0b879c4");
+        let result = rt.block_on(get_musicbrainz_id_for_audio_data(
+            &mut resolver,
+            test,
+            true,
+            &cache,
+        ));
+        assert!(result.is_err());
     }
 
     #[test]
-    fn test_get_recording_mbid_artist_partial_name() {
+    fn test_get_recording_mbid_uses_cache_before_resolver() {
         let test = AudioFileData {
-            artist: "Sasha Sloan".parse().unwrap(),
-            title: "Dancing with Your Ghost".parse().unwrap(),
+            artist: "Ed Sheeran".to_string(),
+            title: "Perfect".to_string(),
             album: None,
         };
-        let mut test_client = ListenbrainzClient::new("".to_string());
+        let expected = Uuid::new_v4();
+        let cache = Mutex::new(ResolutionCache::default());
+        cache
+            .lock()
+            .unwrap()
+            .store_recording("Ed Sheeran", "Perfect", None, Some(expected));
+
+        // An empty resolver that would fail if actually queried.
+        let mut resolver = MockResolver::default();
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let result = rt.block_on(async {
-            get_musicbrainz_id_for_audio_data(&mut test_client, test)
-                .await
-                .unwrap()
-        });
-        assert_eq!(result.to_string(), "9ae71082-ac47-4b9c-a12b-a67fff75784a");
+        let result = rt
+            .block_on(get_musicbrainz_id_for_audio_data(
+                &mut resolver,
+                test,
+                true,
+                &cache,
+            ))
+            .unwrap();
+        assert_eq!(result, expected);
     }
 
     #[test]
-    #[should_panic]
-    fn test_get_recording_mbid_fail_1() {
+    fn test_get_recording_mbid_does_not_reuse_cache_across_albums() {
+        let expected = Uuid::new_v4();
+        let cache = Mutex::new(ResolutionCache::default());
+        // A same-titled track from a different release was already resolved and cached.
+        cache.lock().unwrap().store_recording(
+            "Various Artists",
+            "Title Track",
+            Some("Other Release"),
+            Some(Uuid::new_v4()),
+        );
+
         let test = AudioFileData {
-            artist: "Ed Sheeran".parse().unwrap(),
-            title: "Asdjkhfgds".parse().unwrap(),
-            album: None,
+            artist: "Various Artists".to_string(),
+            title: "Title Track".to_string(),
+            album: Some("This Release".to_string()),
         };
-        let mut test_client = ListenbrainzClient::new("".to_string());
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(async {
-            get_musicbrainz_id_for_audio_data(&mut test_client, test)
-                .await
-                .unwrap()
-        });
-    }
-
-    #[test]
-    fn test_get_artist_mbid_1() {
-        let test = "Ed Sheeran".to_string();
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        let result = rt.block_on(async { get_artist_mbid(test).await });
-        assert_eq!(
-            result.mbid.unwrap().to_string(),
-            "b8a7c51f-362c-4dcb-a259-bc6e0095f0a6"
+        let mut resolver = MockResolver::default();
+        resolver.recordings.insert(
+            ("Various Artists".to_string(), "Title Track".to_string()),
+            expected,
         );
-    }
 
-    #[test]
-    fn test_get_artist_mbid_2_non_english_with_alias() {
-        let test = "Akihito Okano".to_string();
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let result = rt.block_on(async { get_artist_mbid(test).await });
-        assert_eq!(
-            result.mbid.unwrap().to_string(),
-            "0f51ab24-c89a-438e-b3af-2d974fa0654a"
-        );
+        let result = rt
+            .block_on(get_musicbrainz_id_for_audio_data(
+                &mut resolver,
+                test,
+                true,
+                &cache,
+            ))
+            .unwrap();
+        assert_eq!(result, expected);
     }
 }
@@ -0,0 +1,225 @@
+use crate::audio_data::ArtistData;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+const CACHE_FILE_NAME: &str = "resolution_cache.json";
+
+/// How long a negative (not-found) result is trusted before we're willing to retry it.
+const NEGATIVE_RESULT_TTL_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// The outcome of looking a key up in the cache.
+pub enum CacheLookup<T> {
+    /// Previously resolved successfully.
+    Hit(T),
+    /// Previously failed to resolve, and that result hasn't expired yet.
+    Negative,
+    /// Not cached, or a negative result that has expired and should be retried.
+    Miss,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry<T> {
+    result: Option<T>,
+    checked_at: u64,
+}
+
+/// A persistent, on-disk cache of resolved recording and artist lookups, so reruns
+/// over a mostly-unchanged library don't have to re-hit ListenBrainz/MusicBrainz (and
+/// pay the rate limit) for files that were already resolved last time.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ResolutionCache {
+    #[serde(default)]
+    recordings: HashMap<String, CacheEntry<Uuid>>,
+    #[serde(default)]
+    artists: HashMap<String, CacheEntry<ArtistData>>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl ResolutionCache {
+    /// Loads the cache from next to `config_path`. A missing or unreadable file just
+    /// yields an empty cache; `refresh` discards any existing cache outright so every
+    /// lookup is forced to hit the network again.
+    pub fn load(config_path: &Path, refresh: bool) -> Self {
+        let path = cache_path_for(config_path);
+        if refresh {
+            return ResolutionCache {
+                path,
+                ..Default::default()
+            };
+        }
+
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str::<ResolutionCache>(&contents) {
+                Ok(mut cache) => {
+                    cache.path = path;
+                    cache
+                }
+                Err(e) => {
+                    debug!("Could not parse resolution cache, starting fresh: {}", e);
+                    ResolutionCache {
+                        path,
+                        ..Default::default()
+                    }
+                }
+            },
+            Err(e) => {
+                debug!("Could not read resolution cache, starting fresh: {}", e);
+                ResolutionCache {
+                    path,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    /// Writes the cache back out to disk. Should be called once resolution is done.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(&self.path, json)?;
+        Ok(())
+    }
+
+    pub fn lookup_recording(
+        &self,
+        artist: &str,
+        title: &str,
+        album: Option<&str>,
+    ) -> CacheLookup<Uuid> {
+        match self.recordings.get(&recording_key(artist, title, album)) {
+            Some(entry) => lookup_from_entry(entry),
+            None => CacheLookup::Miss,
+        }
+    }
+
+    pub fn store_recording(
+        &mut self,
+        artist: &str,
+        title: &str,
+        album: Option<&str>,
+        result: Option<Uuid>,
+    ) {
+        self.recordings.insert(
+            recording_key(artist, title, album),
+            CacheEntry {
+                result,
+                checked_at: now_secs(),
+            },
+        );
+    }
+
+    pub fn lookup_artist(&self, artist_name: &str) -> CacheLookup<ArtistData> {
+        match self.artists.get(artist_name) {
+            Some(entry) => lookup_from_entry(entry),
+            None => CacheLookup::Miss,
+        }
+    }
+
+    pub fn store_artist(&mut self, artist_name: &str, data: ArtistData) {
+        let result = data.mbid.is_some().then_some(data);
+        self.artists.insert(
+            artist_name.to_string(),
+            CacheEntry {
+                result,
+                checked_at: now_secs(),
+            },
+        );
+    }
+}
+
+fn lookup_from_entry<T: Clone>(entry: &CacheEntry<T>) -> CacheLookup<T> {
+    match &entry.result {
+        Some(value) => CacheLookup::Hit(value.clone()),
+        None if !is_expired(entry.checked_at) => CacheLookup::Negative,
+        None => CacheLookup::Miss,
+    }
+}
+
+fn recording_key(artist: &str, title: &str, album: Option<&str>) -> String {
+    format!("{artist}\u{1f}{title}\u{1f}{}", album.unwrap_or(""))
+}
+
+fn cache_path_for(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(CACHE_FILE_NAME)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn is_expired(checked_at: u64) -> bool {
+    now_secs().saturating_sub(checked_at) > NEGATIVE_RESULT_TTL_SECS
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_store_and_lookup_recording_hit() {
+        let mut cache = ResolutionCache::default();
+        let mbid = Uuid::new_v4();
+        cache.store_recording("Ed Sheeran", "Perfect", Some("Divide"), Some(mbid));
+        match cache.lookup_recording("Ed Sheeran", "Perfect", Some("Divide")) {
+            CacheLookup::Hit(found) => assert_eq!(found, mbid),
+            _ => panic!("expected a cache hit"),
+        }
+    }
+
+    #[test]
+    fn test_store_and_lookup_recording_negative() {
+        let mut cache = ResolutionCache::default();
+        cache.store_recording("Nobody", "Nothing", None, None);
+        assert!(matches!(
+            cache.lookup_recording("Nobody", "Nothing", None),
+            CacheLookup::Negative
+        ));
+    }
+
+    #[test]
+    fn test_lookup_recording_miss_when_uncached() {
+        assert!(matches!(
+            ResolutionCache::default().lookup_recording("Unknown", "Unknown", None),
+            CacheLookup::Miss
+        ));
+    }
+
+    #[test]
+    fn test_recording_cache_is_scoped_by_album() {
+        let mut cache = ResolutionCache::default();
+        let mbid = Uuid::new_v4();
+        cache.store_recording("Various", "Title", Some("Album A"), Some(mbid));
+        assert!(matches!(
+            cache.lookup_recording("Various", "Title", Some("Album B")),
+            CacheLookup::Miss
+        ));
+        assert!(matches!(
+            cache.lookup_recording("Various", "Title", None),
+            CacheLookup::Miss
+        ));
+    }
+
+    #[test]
+    fn test_store_and_lookup_artist_hit() {
+        let mut cache = ResolutionCache::default();
+        let data = ArtistData {
+            artist_tag: "Ed Sheeran".to_string(),
+            mbid: Some(Uuid::new_v4()),
+        };
+        cache.store_artist("Ed Sheeran", data.clone());
+        match cache.lookup_artist("Ed Sheeran") {
+            CacheLookup::Hit(found) => assert_eq!(found, data),
+            _ => panic!("expected a cache hit"),
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use crate::audio_data::{AudioFileData, AudioIDData};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+use uuid::Uuid;
+
+const RECORDING_URL_PREFIX: &str = "https://musicbrainz.org/recording/";
+
+/// A JSPF (JSON Playlist Format) document, as used natively by ListenBrainz. Only the
+/// fields this tool reads or writes are modelled; any other field is ignored on read
+/// and not written back.
+#[derive(Debug, Serialize, Deserialize)]
+struct JspfDocument {
+    playlist: JspfPlaylist,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JspfPlaylist {
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    creator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    public: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extension: Option<serde_json::Value>,
+    track: Vec<JspfTrack>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JspfTrack {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    identifier: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    creator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    album: Option<String>,
+}
+
+/// Writes the resolved recording MBIDs out as a JSPF playlist, without uploading them
+/// anywhere. Useful as a dry-run/diff artifact, or to hand off to another ListenBrainz
+/// client.
+pub fn export_jspf(path: &Path, playlist_name: &str, public: bool, mbids: &[String]) -> Result<()> {
+    let document = JspfDocument {
+        playlist: JspfPlaylist {
+            title: playlist_name.to_string(),
+            creator: None,
+            annotation: None,
+            public: Some(public),
+            extension: None,
+            track: mbids
+                .iter()
+                .map(|mbid| JspfTrack {
+                    identifier: Some(vec![format!("{RECORDING_URL_PREFIX}{mbid}")]),
+                    title: None,
+                    creator: None,
+                    album: None,
+                })
+                .collect(),
+        },
+    };
+
+    let json = serde_json::to_string_pretty(&document)?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+/// Reads a JSPF playlist back as an input source. Tracks that already carry a
+/// MusicBrainz recording identifier resolve straight to an MBID; everything else falls
+/// back to artist/title/album tags, same as a local audio file would.
+pub fn load_jspf(path: &Path) -> Result<Vec<AudioIDData>> {
+    let contents = std::fs::read_to_string(path)?;
+    let document: JspfDocument = serde_json::from_str(&contents)?;
+
+    document
+        .playlist
+        .track
+        .into_iter()
+        .map(|track| {
+            if let Some(mbid) = track
+                .identifier
+                .as_deref()
+                .and_then(recording_mbid_from_identifiers)
+            {
+                return Ok(AudioIDData::Mbid(mbid));
+            }
+
+            let artist = track.creator.ok_or_else(|| {
+                anyhow!("JSPF track has neither a recording identifier nor a creator tag")
+            })?;
+            let title = track
+                .title
+                .ok_or_else(|| anyhow!("JSPF track is missing a title"))?;
+            Ok(AudioIDData::AudioFileData(AudioFileData {
+                artist,
+                title,
+                album: track.album,
+            }))
+        })
+        .collect()
+}
+
+/// Extracts a MusicBrainz recording MBID from a JSPF track identifier, if present.
+fn recording_mbid_from_identifiers(identifiers: &[String]) -> Option<Uuid> {
+    identifiers
+        .iter()
+        .find_map(|id| id.strip_prefix(RECORDING_URL_PREFIX))
+        .and_then(|mbid| Uuid::from_str(mbid).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A throwaway path under the system temp dir, unique per test run.
+    fn temp_jspf_path() -> PathBuf {
+        std::env::temp_dir().join(format!("jspf-test-{}.jspf", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_export_then_load_jspf_round_trips_mbids() {
+        let mbid = Uuid::new_v4();
+        let path = temp_jspf_path();
+        export_jspf(&path, "My Playlist", true, &[mbid.to_string()]).unwrap();
+
+        let tracks = load_jspf(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(tracks, vec![AudioIDData::Mbid(mbid)]);
+    }
+
+    #[test]
+    fn test_load_jspf_falls_back_to_artist_title_album() {
+        let path = temp_jspf_path();
+        std::fs::write(
+            &path,
+            r#"{"playlist":{"title":"x","track":[
+                {"creator":"Ed Sheeran","title":"Perfect","album":"Divide"}
+            ]}}"#,
+        )
+        .unwrap();
+
+        let tracks = load_jspf(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            tracks,
+            vec![AudioIDData::AudioFileData(AudioFileData {
+                artist: "Ed Sheeran".to_string(),
+                title: "Perfect".to_string(),
+                album: Some("Divide".to_string()),
+            })]
+        );
+    }
+
+    #[test]
+    fn test_load_jspf_fails_when_track_has_no_identifier_or_creator() {
+        let path = temp_jspf_path();
+        std::fs::write(
+            &path,
+            r#"{"playlist":{"title":"x","track":[{"title":"Perfect"}]}}"#,
+        )
+        .unwrap();
+
+        let result = load_jspf(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_recording_mbid_from_identifiers_extracts_musicbrainz_url() {
+        let mbid = Uuid::new_v4();
+        let identifiers = vec![
+            "https://example.com/not-it".to_string(),
+            format!("{RECORDING_URL_PREFIX}{mbid}"),
+        ];
+        assert_eq!(recording_mbid_from_identifiers(&identifiers), Some(mbid));
+    }
+
+    #[test]
+    fn test_recording_mbid_from_identifiers_none_when_absent() {
+        let identifiers = vec!["https://example.com/not-it".to_string()];
+        assert_eq!(recording_mbid_from_identifiers(&identifiers), None);
+    }
+}
@@ -1,9 +1,18 @@
 mod audio_data;
+mod cache;
+mod jspf;
+mod listenbrainz_client;
+mod matching;
 mod playlist;
+mod resolver;
+mod spotify;
 
+use crate::cache::ResolutionCache;
+use crate::listenbrainz_client::ListenbrainzClient;
 use crate::playlist::{get_current_playlists, get_current_user};
+use crate::resolver::{ListenbrainzResolver, MetadataResolver};
 use anyhow::{Error, Result};
-use audio_data::AudioFileData;
+use audio_data::AudioIDData;
 use clap::{Parser, ValueEnum};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
 use config::Config;
@@ -20,12 +29,15 @@ use reqwest::header::AUTHORIZATION;
 use serde_json::json;
 use std::path::PathBuf;
 use std::process::exit;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio;
+use tokio::sync::Mutex as AsyncMutex;
 
 #[derive(Parser, Debug)]
 struct Args {
+    /// An M3U playlist path, a Spotify playlist URL/URI (with `--source spotify`), or a
+    /// JSPF playlist path (with `--source jspf`).
     file: PathBuf,
     #[arg(short, long, default_value = "./config.toml")]
     config: PathBuf,
@@ -34,6 +46,20 @@ struct Args {
     feedback: Option<Feedback>,
     #[arg(short, long, default_value_t = false)]
     public: bool,
+    /// Where to read the input playlist from.
+    #[arg(value_enum, long, default_value_t = Source::M3u)]
+    source: Source,
+    /// Write the resolved recording MBIDs out as a local JSPF playlist instead of
+    /// uploading them to ListenBrainz.
+    #[arg(long)]
+    export: Option<PathBuf>,
+    /// Disable disambiguation prompts and fall back to the best automatic match, for
+    /// scripted/unattended runs.
+    #[arg(long, visible_alias = "yes", default_value_t = false)]
+    non_interactive: bool,
+    /// Ignore the on-disk resolution cache and re-resolve every track from scratch.
+    #[arg(long, default_value_t = false)]
+    refresh_cache: bool,
     #[command(flatten)]
     verbose: Verbosity<InfoLevel>,
 }
@@ -46,6 +72,15 @@ enum Feedback {
     NEUTRAL = 0,
 }
 
+/// Where the input playlist comes from.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[clap(rename_all = "lowercase")]
+enum Source {
+    M3u,
+    Spotify,
+    Jspf,
+}
+
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
@@ -56,11 +91,16 @@ async fn main() {
         .init();
 
     let settings = Config::builder()
-        .add_source(config::File::from(args.config))
+        .add_source(config::File::from(args.config.clone()))
         .build()
         .expect("Could not read configuration");
 
-    if !args.file.exists() {
+    let cache = Arc::new(Mutex::new(ResolutionCache::load(
+        &args.config,
+        args.refresh_cache,
+    )));
+
+    if args.source == Source::M3u && !args.file.exists() {
         error!("Given playlist file doesn't exist");
         exit(1);
     }
@@ -84,35 +124,86 @@ async fn main() {
     };
     info!("This token belongs to {}!", &user_name);
 
-    let file_path = &args.file;
-    let playlist_entries = load_file_paths(file_path);
-    let number_of_files = playlist_entries.len();
-    info!("Found {} files in playlist", number_of_files);
+    let resolver = Arc::new(AsyncMutex::new(ListenbrainzResolver::new(
+        ListenbrainzClient::new(token.clone()),
+    )));
 
-    if number_of_files == 0 {
-        error!("No files read from playlist, aborting");
-        exit(1);
-    }
+    let song_data: Vec<AudioIDData> = match args.source {
+        Source::M3u => {
+            let file_path = &args.file;
+            let playlist_entries = load_file_paths(file_path);
+            let number_of_files = playlist_entries.len();
+            info!("Found {} files in playlist", number_of_files);
 
-    let song_data: Vec<_> = playlist_entries
-        .into_iter()
-        .flat_map(|e| audio_data::load_tags_from_file_path(e))
-        .collect();
-    let number_of_tagged_songs = song_data.len();
-    let percentage = calculate_percentage(number_of_tagged_songs, number_of_files)
-        .expect("Could not calculate percentage of tagged songs");
-    info!(
-        "{}/{} ({:.2}%) of songs had readable tags",
-        number_of_tagged_songs, number_of_files, percentage,
-    );
+            if number_of_files == 0 {
+                error!("No files read from playlist, aborting");
+                exit(1);
+            }
 
-    if number_of_tagged_songs == 0 {
-        error!("No tagged songs could be read, aborting");
-        exit(1);
-    }
+            let song_data: Vec<_> = playlist_entries
+                .into_iter()
+                .flat_map(|e| audio_data::load_tags_from_file_path(e))
+                .collect();
+            let number_of_tagged_songs = song_data.len();
+            let percentage = calculate_percentage(number_of_tagged_songs, number_of_files)
+                .expect("Could not calculate percentage of tagged songs");
+            info!(
+                "{}/{} ({:.2}%) of songs had readable tags",
+                number_of_tagged_songs, number_of_files, percentage,
+            );
+
+            if number_of_tagged_songs == 0 {
+                error!("No tagged songs could be read, aborting");
+                exit(1);
+            }
+            song_data
+        }
+        Source::Spotify => {
+            let playlist = args.file.to_string_lossy().to_string();
+            match spotify::load_spotify_playlist(&settings, &playlist).await {
+                Ok(tracks) => {
+                    info!("Found {} tracks in Spotify playlist", tracks.len());
+                    if tracks.is_empty() {
+                        error!("No tracks read from Spotify playlist, aborting");
+                        exit(1);
+                    }
+                    tracks.into_iter().map(AudioIDData::AudioFileData).collect()
+                }
+                Err(e) => {
+                    error!("Could not load Spotify playlist: {}", e);
+                    exit(1);
+                }
+            }
+        }
+        Source::Jspf => match jspf::load_jspf(&args.file) {
+            Ok(tracks) => {
+                info!("Found {} tracks in JSPF playlist", tracks.len());
+                if tracks.is_empty() {
+                    error!("No tracks read from JSPF playlist, aborting");
+                    exit(1);
+                }
+                tracks
+            }
+            Err(e) => {
+                error!("Could not load JSPF playlist: {}", e);
+                exit(1);
+            }
+        },
+    };
 
     info!("Resolving song tags to Musicbrainz IDs...");
-    let musicbrainz_ids = resolve_all_songs_for_mbids(song_data).await;
+    let number_of_tagged_songs = song_data.len();
+    let musicbrainz_ids = resolve_all_songs_for_mbids(
+        song_data,
+        args.non_interactive,
+        Arc::clone(&cache),
+        Arc::clone(&resolver),
+    )
+    .await;
+
+    if let Err(e) = cache.lock().unwrap().save() {
+        error!("Could not save resolution cache: {}", e);
+    }
 
     let number_of_resolved_songs = musicbrainz_ids.len();
     let percentage = calculate_percentage(number_of_resolved_songs, number_of_tagged_songs)
@@ -122,6 +213,14 @@ async fn main() {
         number_of_resolved_songs, number_of_tagged_songs, percentage,
     );
 
+    if let Some(export_path) = &args.export {
+        match jspf::export_jspf(export_path, &args.playlist_name, args.public, &musicbrainz_ids) {
+            Ok(()) => info!("Exported resolved playlist to {}", export_path.display()),
+            Err(e) => error!("Could not export JSPF playlist: {}", e),
+        }
+        return;
+    }
+
     match Confirm::new("Do you want to continue with the matched songs?")
         .with_default(true)
         .prompt()
@@ -206,7 +305,12 @@ async fn give_feedback_on_all_songs(
         }
     }
 }
-async fn resolve_all_songs_for_mbids(song_data: Vec<AudioFileData>) -> Vec<String> {
+async fn resolve_all_songs_for_mbids<R: MetadataResolver + Send + 'static>(
+    song_data: Vec<AudioIDData>,
+    non_interactive: bool,
+    cache: Arc<Mutex<ResolutionCache>>,
+    resolver: Arc<AsyncMutex<R>>,
+) -> Vec<String> {
     // Be a good internet citizen; this isn't an important application.
     let rate_limiter = Arc::new(RateLimiter::direct(
         Quota::with_period(Duration::from_secs(5)).expect("Could not create quota"),
@@ -218,9 +322,30 @@ async fn resolve_all_songs_for_mbids(song_data: Vec<AudioFileData>) -> Vec<Strin
         .map(|data| {
             let limiter = Arc::clone(&rate_limiter);
             let pb = Arc::clone(&progress_bar);
+            let cache = Arc::clone(&cache);
+            let resolver = Arc::clone(&resolver);
             async move {
+                // A file that already carried an embedded MusicBrainz tag doesn't need
+                // resolving at all.
+                let audio_file_data = match data {
+                    AudioIDData::Mbid(mbid) => {
+                        pb.inc(1);
+                        return Ok(mbid.to_string());
+                    }
+                    AudioIDData::AudioFileData(data) => data,
+                };
+
                 limiter.until_ready().await;
-                let out = audio_data::get_musicbrainz_id_for_audio_data(data).await;
+                let mut resolver = resolver.lock().await;
+                let out = audio_data::get_musicbrainz_id_for_audio_data(
+                    &mut *resolver,
+                    audio_file_data,
+                    non_interactive,
+                    &cache,
+                )
+                .await
+                .map(|mbid| mbid.to_string());
+                drop(resolver);
                 pb.inc(1);
                 out
             }
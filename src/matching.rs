@@ -0,0 +1,180 @@
+use inquire::Select;
+use strsim::jaro_winkler;
+
+/// A candidate result annotated with a `0..=100` confidence score, used whenever a
+/// lookup can return more than one plausible entity and we need to pick deliberately
+/// rather than just taking whatever came back first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<T> {
+    pub score: u8,
+    pub item: T,
+}
+
+/// Below this confidence a match is considered too uncertain to accept automatically.
+pub const DEFAULT_MATCH_THRESHOLD: f64 = 0.7;
+
+const MB_SCORE_WEIGHT: f64 = 0.5;
+const SIMILARITY_WEIGHT: f64 = 0.5;
+
+/// Candidates scoring within this many points of each other are treated as a tie.
+const TIE_EPSILON: u8 = 2;
+
+/// Combines a MusicBrainz search `score` (0-100) with a client-side string similarity
+/// between the query and the candidate's name into a single `0..=100` confidence score.
+pub fn combined_score(mb_score: u8, query: &str, candidate_name: &str) -> u8 {
+    let similarity = jaro_winkler(&query.to_lowercase(), &candidate_name.to_lowercase());
+    let combined = MB_SCORE_WEIGHT * (mb_score as f64 / 100.0) + SIMILARITY_WEIGHT * similarity;
+    (combined.clamp(0.0, 1.0) * 100.0).round() as u8
+}
+
+/// Sorts candidates by their combined score, descending. Candidates within
+/// [`TIE_EPSILON`] of each other are instead ordered by `raw_mb_score`, so an exact
+/// MusicBrainz hit wins over a merely similar-looking name.
+pub fn rank_matches<T>(
+    mut candidates: Vec<Match<T>>,
+    raw_mb_score: impl Fn(&T) -> u8,
+) -> Vec<Match<T>> {
+    candidates.sort_by(|a, b| {
+        if a.score.abs_diff(b.score) <= TIE_EPSILON {
+            raw_mb_score(&b.item).cmp(&raw_mb_score(&a.item))
+        } else {
+            b.score.cmp(&a.score)
+        }
+    });
+    candidates
+}
+
+/// Returns the top-ranked candidate, but only if it clears `threshold` (`0.0..=1.0`).
+pub fn best_above_threshold<T>(ranked: Vec<Match<T>>, threshold: f64) -> Option<Match<T>> {
+    let cutoff = (threshold.clamp(0.0, 1.0) * 100.0).round() as u8;
+    match ranked.into_iter().next() {
+        Some(m) if m.score >= cutoff => Some(m),
+        _ => None,
+    }
+}
+
+/// Top candidates within this many points of each other are considered ambiguous
+/// enough to ask the user about, rather than silently picking the higher one.
+const AMBIGUITY_MARGIN: u8 = 5;
+
+/// How many candidates to offer in the disambiguation prompt.
+const MAX_PROMPT_CANDIDATES: usize = 5;
+
+const SKIP_OPTION: &str = "Skip this track";
+
+/// Picks a candidate out of `ranked`. If the top candidate clears `threshold` and is
+/// not within [`AMBIGUITY_MARGIN`] of the runner-up, it's returned automatically.
+/// Otherwise, unless `non_interactive` is set, the user is shown the top candidates
+/// (formatted by `label`) via an interactive prompt and can pick one or skip the track.
+pub fn resolve_candidate<T>(
+    ranked: Vec<Match<T>>,
+    threshold: f64,
+    non_interactive: bool,
+    label: impl Fn(&T) -> String,
+) -> Option<T> {
+    let cutoff = (threshold.clamp(0.0, 1.0) * 100.0).round() as u8;
+    let is_confident = match ranked.as_slice() {
+        [top, rest @ ..] => {
+            top.score >= cutoff
+                && rest
+                    .first()
+                    .map_or(true, |second| top.score.abs_diff(second.score) > AMBIGUITY_MARGIN)
+        }
+        [] => false,
+    };
+
+    if is_confident || non_interactive {
+        return best_above_threshold(ranked, threshold).map(|m| m.item);
+    }
+
+    let mut options: Vec<String> = ranked
+        .iter()
+        .take(MAX_PROMPT_CANDIDATES)
+        .enumerate()
+        .map(|(i, m)| format!("{}. {} ({}% confidence)", i + 1, label(&m.item), m.score))
+        .collect();
+    options.push(SKIP_OPTION.to_string());
+
+    let choice = Select::new("Multiple possible matches found, which is correct?", options).prompt();
+    match choice {
+        Ok(selected) if selected != SKIP_OPTION => {
+            let index: usize = selected.split('.').next()?.trim().parse().ok()?;
+            ranked.into_iter().nth(index - 1).map(|m| m.item)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_combined_score_exact_match_is_high() {
+        let score = combined_score(100, "Ed Sheeran", "Ed Sheeran");
+        assert_eq!(score, 100);
+    }
+
+    #[test]
+    fn test_combined_score_unrelated_name_scores_below_exact_match() {
+        // The MB score contributes its full 50 points; the Jaro-Winkler similarity of
+        // these names is about 0.6, which adds 30 rather than 50.
+        let score = combined_score(100, "Ed Sheeran", "Florence + the Machine");
+        assert_eq!(score, 80);
+    }
+
+    #[test]
+    fn test_rank_matches_sorts_descending() {
+        let candidates = vec![
+            Match { score: 40, item: "low" },
+            Match { score: 90, item: "high" },
+            Match { score: 60, item: "mid" },
+        ];
+        let ranked = rank_matches(candidates, |_| 0);
+        assert_eq!(ranked[0].item, "high");
+        assert_eq!(ranked[1].item, "mid");
+        assert_eq!(ranked[2].item, "low");
+    }
+
+    #[test]
+    fn test_rank_matches_breaks_ties_with_raw_mb_score() {
+        let candidates = vec![
+            Match { score: 80, item: ("a", 60) },
+            Match { score: 81, item: ("b", 95) },
+        ];
+        let ranked = rank_matches(candidates, |item| item.1);
+        assert_eq!(ranked[0].item.0, "b");
+    }
+
+    #[test]
+    fn test_best_above_threshold_rejects_low_confidence() {
+        let ranked = vec![Match { score: 50, item: "maybe" }];
+        assert_eq!(best_above_threshold(ranked, DEFAULT_MATCH_THRESHOLD), None);
+    }
+
+    #[test]
+    fn test_best_above_threshold_accepts_confident_match() {
+        let ranked = vec![Match { score: 95, item: "definitely" }];
+        assert_eq!(
+            best_above_threshold(ranked, DEFAULT_MATCH_THRESHOLD),
+            Some(Match { score: 95, item: "definitely" })
+        );
+    }
+
+    #[test]
+    fn test_resolve_candidate_returns_confident_top_without_prompting() {
+        let ranked = vec![
+            Match { score: 95, item: "clear winner" },
+            Match { score: 40, item: "distant second" },
+        ];
+        let result = resolve_candidate(ranked, DEFAULT_MATCH_THRESHOLD, false, |s| s.to_string());
+        assert_eq!(result, Some("clear winner"));
+    }
+
+    #[test]
+    fn test_resolve_candidate_non_interactive_falls_back_to_best() {
+        let ranked = vec![Match { score: 80, item: "a" }, Match { score: 78, item: "b" }];
+        let result = resolve_candidate(ranked, DEFAULT_MATCH_THRESHOLD, true, |s| s.to_string());
+        assert_eq!(result, Some("a"));
+    }
+}
@@ -0,0 +1,335 @@
+use crate::audio_data::ArtistData;
+use crate::listenbrainz_client::ListenbrainzClient;
+use crate::matching::{
+    combined_score, rank_matches, resolve_candidate, Match, DEFAULT_MATCH_THRESHOLD,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use musicbrainz_rs::entity::artist::{Artist, ArtistSearchQuery};
+use musicbrainz_rs::entity::recording::{Recording, RecordingSearchQuery};
+use musicbrainz_rs::Search;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+use url::Url;
+use uuid::Uuid;
+
+/// Abstracts ListenBrainz/MusicBrainz metadata lookups behind a trait, so the
+/// resolution logic in `audio_data` can be unit-tested without making real network
+/// calls.
+#[async_trait]
+pub trait MetadataResolver {
+    /// Looks up a single recording by artist/title (and optionally release/album)
+    /// via the ListenBrainz metadata lookup endpoint. The response is scored against
+    /// the query and gated by [`DEFAULT_MATCH_THRESHOLD`] the same way the search
+    /// engines are, so a loose match triggers disambiguation instead of being trusted
+    /// outright. Returns `None` on a no-match or a rejected/skipped low-confidence one.
+    async fn lookup_recording(
+        &mut self,
+        title: &str,
+        artist: &str,
+        album: Option<&str>,
+        non_interactive: bool,
+    ) -> Result<Option<Uuid>>;
+
+    /// Searches MusicBrainz for an artist by name, falling back to an alias search,
+    /// and picks a candidate the same way recording lookups do.
+    async fn search_artist(&self, name: &str, non_interactive: bool) -> Result<ArtistData>;
+
+    /// A fallback engine: searches MusicBrainz directly for a recording by
+    /// title/artist/release, independent of the ListenBrainz lookup endpoint. Used
+    /// when the direct and alias-retry lookups both come up empty.
+    async fn search_recording(
+        &self,
+        title: &str,
+        artist: &str,
+        album: Option<&str>,
+        non_interactive: bool,
+    ) -> Result<Option<Uuid>>;
+}
+
+/// The real [`MetadataResolver`], backed by the ListenBrainz/MusicBrainz HTTP APIs.
+pub struct ListenbrainzResolver {
+    client: ListenbrainzClient,
+}
+
+impl ListenbrainzResolver {
+    pub fn new(client: ListenbrainzClient) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl MetadataResolver for ListenbrainzResolver {
+    async fn lookup_recording(
+        &mut self,
+        title: &str,
+        artist: &str,
+        album: Option<&str>,
+        non_interactive: bool,
+    ) -> Result<Option<Uuid>> {
+        let mut params = vec![("artist_name", artist), ("recording_name", title)];
+        if let Some(album) = album {
+            params.push(("release_name", album));
+        }
+        let request_url: Url =
+            Url::parse_with_params("https://api.listenbrainz.org/1/metadata/lookup/", &params)?;
+        let result = self
+            .client
+            .take_request_builder(self.client.request_client.get(request_url))
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+
+        let Some(candidate) = score_lookup_result(artist, title, result) else {
+            return Ok(None);
+        };
+
+        let ranked = rank_matches(vec![candidate], |_| 100);
+        let chosen = resolve_candidate(ranked, DEFAULT_MATCH_THRESHOLD, non_interactive, |value| {
+            format!(
+                "{} - {} ({})",
+                value
+                    .get("artist_credit_name")
+                    .and_then(Value::as_str)
+                    .unwrap_or(artist),
+                value
+                    .get("recording_name")
+                    .and_then(Value::as_str)
+                    .unwrap_or(title),
+                value
+                    .get("recording_mbid")
+                    .and_then(Value::as_str)
+                    .unwrap_or("unknown mbid"),
+            )
+        });
+
+        let Some(value) = chosen else {
+            return Ok(None);
+        };
+        let mbid = value
+            .get("recording_mbid")
+            .and_then(Value::as_str)
+            .ok_or_else(|| {
+                anyhow::anyhow!("Could not extract recording MBID from JSON: {:?}", value)
+            })?;
+        Ok(Some(Uuid::from_str(mbid)?))
+    }
+
+    async fn search_artist(&self, name: &str, non_interactive: bool) -> Result<ArtistData> {
+        let query = ArtistSearchQuery::query_builder().artist(name).build();
+        let mut result = Artist::search(query).execute().await?;
+
+        // If no results found, find an alias instead
+        if result.count <= 0 {
+            let query = ArtistSearchQuery::query_builder().alias(name).build();
+            result = Artist::search(query).execute().await?;
+        }
+
+        if result.count <= 0 {
+            return Ok(ArtistData {
+                artist_tag: name.to_string(),
+                mbid: None,
+            });
+        }
+
+        let candidates = result
+            .entities
+            .into_iter()
+            .map(|artist| {
+                let score = combined_score(artist.score.unwrap_or(0), name, &artist.name);
+                Match {
+                    score,
+                    item: artist,
+                }
+            })
+            .collect();
+        let ranked = rank_matches(candidates, |artist| artist.score.unwrap_or(0));
+
+        let chosen =
+            resolve_candidate(ranked, DEFAULT_MATCH_THRESHOLD, non_interactive, |artist| {
+                format!("{} (mbid {})", artist.name, artist.id)
+            });
+
+        Ok(match chosen {
+            Some(artist) => ArtistData {
+                artist_tag: artist.name,
+                mbid: Some(Uuid::from_str(artist.id.as_str())?),
+            },
+            None => ArtistData {
+                artist_tag: name.to_string(),
+                mbid: None,
+            },
+        })
+    }
+
+    async fn search_recording(
+        &self,
+        title: &str,
+        artist: &str,
+        album: Option<&str>,
+        non_interactive: bool,
+    ) -> Result<Option<Uuid>> {
+        let mut query_builder = RecordingSearchQuery::query_builder();
+        query_builder.recording(title).and().artist(artist);
+        if let Some(album) = album {
+            query_builder.and().release(album);
+        }
+        let query = query_builder.build();
+        let result = Recording::search(query).execute().await?;
+
+        if result.count <= 0 {
+            return Ok(None);
+        }
+
+        let candidates = result
+            .entities
+            .into_iter()
+            .map(|recording| {
+                let recording_artist = recording_artist_credit(&recording);
+                let artist_score =
+                    combined_score(recording.score.unwrap_or(0), artist, &recording_artist);
+                let title_score =
+                    combined_score(recording.score.unwrap_or(0), title, &recording.title);
+                let score = ((artist_score as u16 + title_score as u16) / 2) as u8;
+                Match {
+                    score,
+                    item: recording,
+                }
+            })
+            .collect();
+        let ranked = rank_matches(candidates, |recording| recording.score.unwrap_or(0));
+
+        let chosen = resolve_candidate(
+            ranked,
+            DEFAULT_MATCH_THRESHOLD,
+            non_interactive,
+            |recording| {
+                format!(
+                    "{} - {} ({})",
+                    recording_artist_credit(recording),
+                    recording.title,
+                    recording.id
+                )
+            },
+        );
+
+        chosen
+            .map(|recording| Uuid::from_str(&recording.id))
+            .transpose()
+            .map_err(Into::into)
+    }
+}
+
+/// Scores a single ListenBrainz lookup response against the query that produced it, by
+/// comparing the returned artist/recording names to the ones we asked for. Returns
+/// `None` for an empty (no-match) response.
+fn score_lookup_result(artist: &str, title: &str, result: Value) -> Option<Match<Value>> {
+    if result.as_object().map_or(true, |o| o.is_empty()) {
+        return None;
+    }
+
+    let returned_artist = result
+        .get("artist_credit_name")
+        .and_then(Value::as_str)
+        .unwrap_or(artist);
+    let returned_title = result
+        .get("recording_name")
+        .and_then(Value::as_str)
+        .unwrap_or(title);
+
+    let artist_score = combined_score(100, artist, returned_artist);
+    let title_score = combined_score(100, title, returned_title);
+    let score = ((artist_score as u16 + title_score as u16) / 2) as u8;
+
+    Some(Match { score, item: result })
+}
+
+fn recording_artist_credit(recording: &Recording) -> String {
+    recording
+        .artist_credit
+        .as_ref()
+        .and_then(|credits| credits.first())
+        .map(|credit| credit.name.clone())
+        .unwrap_or_default()
+}
+
+/// A canned, offline [`MetadataResolver`] for tests: looks answers up in in-memory
+/// maps instead of making network calls. Anything not present is treated as a miss.
+#[derive(Default)]
+pub struct MockResolver {
+    pub recordings: HashMap<(String, String), Uuid>,
+    pub artists: HashMap<String, ArtistData>,
+    pub recording_search_results: HashMap<(String, String), Uuid>,
+}
+
+#[async_trait]
+impl MetadataResolver for MockResolver {
+    async fn lookup_recording(
+        &mut self,
+        title: &str,
+        artist: &str,
+        _album: Option<&str>,
+        _non_interactive: bool,
+    ) -> Result<Option<Uuid>> {
+        Ok(self
+            .recordings
+            .get(&(artist.to_string(), title.to_string()))
+            .copied())
+    }
+
+    async fn search_artist(&self, name: &str, _non_interactive: bool) -> Result<ArtistData> {
+        Ok(self.artists.get(name).cloned().unwrap_or(ArtistData {
+            artist_tag: name.to_string(),
+            mbid: None,
+        }))
+    }
+
+    async fn search_recording(
+        &self,
+        title: &str,
+        artist: &str,
+        _album: Option<&str>,
+        _non_interactive: bool,
+    ) -> Result<Option<Uuid>> {
+        Ok(self
+            .recording_search_results
+            .get(&(artist.to_string(), title.to_string()))
+            .copied())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_score_lookup_result_is_none_for_empty_response() {
+        assert!(score_lookup_result("Ed Sheeran", "Perfect", json!({})).is_none());
+    }
+
+    #[test]
+    fn test_score_lookup_result_scores_exact_match_high() {
+        let result = json!({
+            "artist_credit_name": "Ed Sheeran",
+            "recording_name": "Perfect",
+            "recording_mbid": "b9a5a7b4-c2bb-4f2a-8c8f-d0e8f2c5a1a1",
+        });
+        let scored = score_lookup_result("Ed Sheeran", "Perfect", result).unwrap();
+        assert_eq!(scored.score, 100);
+    }
+
+    #[test]
+    fn test_score_lookup_result_scores_mismatched_artist_below_exact_match() {
+        let result = json!({
+            "artist_credit_name": "Florence + the Machine",
+            "recording_name": "Perfect",
+            "recording_mbid": "b9a5a7b4-c2bb-4f2a-8c8f-d0e8f2c5a1a1",
+        });
+        // The artist half scores 80 and the matching title half 100, averaging to 90.
+        let scored = score_lookup_result("Ed Sheeran", "Perfect", result).unwrap();
+        assert_eq!(scored.score, 90);
+    }
+}
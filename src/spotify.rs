@@ -0,0 +1,52 @@
+use crate::audio_data::AudioFileData;
+use anyhow::{anyhow, Result};
+use config::Config;
+use futures::TryStreamExt;
+use rspotify::clients::BaseClient;
+use rspotify::model::{PlayableItem, PlaylistId};
+use rspotify::{ClientCredsSpotify, Credentials};
+
+/// Pulls the tracks of a public Spotify playlist via client-credentials auth (no user
+/// login required) and maps them into the same `AudioFileData` shape local files
+/// produce, so they can be fed into the existing resolution pipeline.
+pub async fn load_spotify_playlist(
+    settings: &Config,
+    playlist: &str,
+) -> Result<Vec<AudioFileData>> {
+    let client_id = settings
+        .get_string("spotify_client_id")
+        .map_err(|_| anyhow!("Configuration does not contain a spotify_client_id!"))?;
+    let client_secret = settings
+        .get_string("spotify_client_secret")
+        .map_err(|_| anyhow!("Configuration does not contain a spotify_client_secret!"))?;
+
+    let client = ClientCredsSpotify::new(Credentials::new(&client_id, &client_secret));
+    client.request_token().await?;
+
+    // Accept an `https://open.spotify.com/playlist/...` link as well as an ID or URI.
+    let playlist = playlist
+        .strip_prefix("https://open.spotify.com/playlist/")
+        .map(|rest| rest.split('?').next().unwrap_or(rest))
+        .unwrap_or(playlist);
+    let playlist_id = PlaylistId::from_id_or_uri(playlist)?;
+    // `playlist_items` pages through the whole playlist (Spotify caps a single response
+    // at 100 tracks), unlike `client.playlist(..)` which only returns the first page.
+    let items: Vec<_> = client
+        .playlist_items(playlist_id, None, None)
+        .try_collect()
+        .await?;
+
+    let tracks = items
+        .into_iter()
+        .filter_map(|item| match item.track {
+            Some(PlayableItem::Track(track)) => Some(AudioFileData {
+                artist: track.artists.first()?.name.clone(),
+                title: track.name,
+                album: Some(track.album.name),
+            }),
+            _ => None,
+        })
+        .collect();
+
+    Ok(tracks)
+}